@@ -6,14 +6,40 @@ use crate::CratePath;
 use darling::ToTokens as _;
 use proc_macro_error::{abort, emit_warning};
 use std::collections::HashMap;
-use syn::{parse_quote, spanned::Spanned as _};
+use syn::{parse_quote, punctuated::Pair, spanned::Spanned as _};
 
 use super::{TypePath, TypePathType};
 
 #[derive(Debug)]
 pub struct TypeSubstitutes {
     pub(crate) inner: HashMap<String, syn::TypePath>,
-    params: HashMap<String, Vec<TypePath>>,
+    params: HashMap<String, Vec<GenericParam>>,
+    /// Off by default: many hand-written substitutes only care to rename or
+    /// reorder a subset of the source's generics, so an unused source
+    /// generic/lifetime isn't necessarily a mistake. Enable with
+    /// [`TypeSubstitutes::warn_on_unused_params`] to surface it anyway.
+    warn_on_unused_params: bool,
+}
+
+/// A single resolved generic argument of a substitute's target path, tagged by
+/// whether the target argument is written as a type, a const or a lifetime
+/// (a bare const ident like `N` parses as a type and is tagged `Type`).
+/// Const arguments aren't substituted like types are: they're only reordered or
+/// forwarded verbatim, since there's nothing to resolve them against.
+#[derive(Debug, Clone)]
+pub enum GenericParam {
+    Type(TypePath),
+    Const(syn::Expr),
+    Lifetime(syn::Lifetime),
+}
+
+/// A single generic argument slot found while walking a `syn::PathArguments`,
+/// distinguishing a type parameter from a const or lifetime generic parameter.
+#[derive(Debug, Clone, Copy)]
+enum GenericSlot<'a> {
+    Type(&'a syn::TypePath),
+    Const(&'a syn::Expr),
+    Lifetime(&'a syn::Lifetime),
 }
 
 impl TypeSubstitutes {
@@ -66,15 +92,23 @@ impl TypeSubstitutes {
                 .map(|(path, substitute)| (path.to_owned(), substitute))
                 .collect(),
             params: Default::default(),
+            warn_on_unused_params: false,
         }
     }
 
+    /// Warn when a substitute's source declares a generic or lifetime parameter
+    /// that the target never references. Off by default.
+    pub fn warn_on_unused_params(&mut self, warn: bool) -> &mut Self {
+        self.warn_on_unused_params = warn;
+        self
+    }
+
     pub fn extend(
         &mut self,
         elems: impl IntoIterator<Item = (syn::TypePath, AbsoluteTypePath)>,
     ) {
         self.inner
-            .extend(elems.into_iter().map(|(ty, AbsoluteTypePath(with))| {
+            .extend(elems.into_iter().map(|(ty, AbsoluteTypePath(mut with))| {
                 // TODO: Verify both paths
                 let src_namespace = || ty.path.segments.iter().rev().skip(1);
                 if let Some(seg) = src_namespace()
@@ -82,23 +116,95 @@ impl TypeSubstitutes {
                 {
                     abort!(seg.arguments.span(), "Namespace segment can't be generic");
                 }
+                let defaulted_idents = strip_defaulted_params(&mut with);
                 let Some(syn::PathSegment { arguments: src_path_args, ..}) = ty.path.segments.last() else { abort!(ty.span(), "Empty path") };
                 let Some(syn::PathSegment { arguments: target_path_args, ..}) = with.path.segments.last() else { abort!(ty.span(), "Empty path") };
 
-                let source_args: Vec<_> = type_args(src_path_args).collect();
+                let source_types: Vec<_> = type_args(src_path_args).collect();
+                let source_slots: Vec<_> = generic_args(src_path_args).collect();
                 // Generics were specified in the source type, so we substitute
                 // them
-                if !source_args.is_empty() {
-                    let new_params = type_args(target_path_args).map(|arg| {
-                        // TODO: Handle nested generics in a substituted path
-                        if let Some(&src) = source_args.iter().find(|&src| src == &arg) {
-                            // TODO: This surely wrongly interacts with unused generics etc.
-                            TypePath::Type(TypePathType::Path { path: src.clone(), params: Vec::new()})
-                         }
-                        else if is_absolute(arg) {
-                            TypePath::Type(TypePathType::Path { path: arg.clone(), params: Vec::new()})
-                        } else {
-                            abort!(arg.span(), "Generic parameter {} couldn't be found or not absolute")
+                if !source_slots.is_empty() {
+                    let source_idents: Vec<_> = source_types
+                        .iter()
+                        .filter_map(|ty| generic_ident(ty))
+                        .collect();
+                    if !source_idents.is_empty() {
+                        let mut used = Vec::new();
+                        validate_target_generics(target_path_args, &source_idents, &mut used);
+                        if self.warn_on_unused_params {
+                            let defaulted_names: Vec<_> = defaulted_idents
+                                .iter()
+                                .map(ToString::to_string)
+                                .collect();
+                            let unused = source_idents
+                                .iter()
+                                .filter(|ident| {
+                                    !used.contains(ident)
+                                        && !defaulted_names.contains(&ident.to_string())
+                                })
+                                .map(|ident| format!("`{ident}`"))
+                                .collect::<Vec<_>>()
+                                .join(", ");
+                            if !unused.is_empty() {
+                                emit_warning!(ty.span(), "unused generic parameter(s) declared by substitute source: {}", unused);
+                            }
+                        }
+                    }
+
+                    let source_lifetimes: Vec<_> = source_slots
+                        .iter()
+                        .filter_map(|slot| match slot {
+                            GenericSlot::Lifetime(lifetime) => Some(*lifetime),
+                            _ => None,
+                        })
+                        .collect();
+                    if !source_lifetimes.is_empty() {
+                        let mut used = Vec::new();
+                        validate_target_lifetimes(target_path_args, &source_lifetimes, &mut used);
+                        if self.warn_on_unused_params && used.len() < source_lifetimes.len() {
+                            let unused = source_lifetimes
+                                .iter()
+                                .filter(|lifetime| !used.contains(lifetime))
+                                .map(|lifetime| format!("`{lifetime}`"))
+                                .collect::<Vec<_>>()
+                                .join(", ");
+                            emit_warning!(ty.span(), "unused lifetime parameter(s) declared by substitute source: {}", unused);
+                        }
+                    }
+
+                    let new_params = generic_args(target_path_args).map(|slot| match slot {
+                        GenericSlot::Type(arg) => {
+                            GenericParam::Type(resolve_substituted_arg(arg, &source_types))
+                        }
+                        // Consts can't be substituted (there's no analogue to an
+                        // absolute path to fall back on); they're only reordered to
+                        // match a same-named source const slot, or else forwarded
+                        // as written.
+                        GenericSlot::Const(expr) => {
+                            let forwarded = source_slots
+                                .iter()
+                                .find_map(|slot| match slot {
+                                    GenericSlot::Const(src) if *src == expr => Some((*src).clone()),
+                                    _ => None,
+                                })
+                                .unwrap_or_else(|| expr.clone());
+                            GenericParam::Const(forwarded)
+                        }
+                        // Lifetimes, like consts, aren't substituted: a reorder just
+                        // means the target references the same source-declared
+                        // lifetime in a different position.
+                        GenericSlot::Lifetime(lifetime) => {
+                            let forwarded = source_slots
+                                .iter()
+                                .find_map(|slot| match slot {
+                                    GenericSlot::Lifetime(src) if src.ident == lifetime.ident => {
+                                        Some((*src).clone())
+                                    }
+                                    _ => None,
+                                })
+                                .unwrap_or_else(|| lifetime.clone());
+                            GenericParam::Lifetime(forwarded)
                         }
                     }).collect();
 
@@ -114,12 +220,24 @@ impl TypeSubstitutes {
     }
 
     /// Given a source type path and the (already resolved? this can't be right)
-    /// type parameters, return a new path and optionally overwritten type parameters
+    /// type parameters, return a new path and optionally overwritten type parameters.
+    ///
+    /// The returned params mirror the shape of the substitute's target path: nested
+    /// generic arguments (e.g. the `Vec<T>` in `Wrapper<Vec<T>>`) are represented as
+    /// their own `TypePathType::Path` with their own `params`, rather than being
+    /// flattened, so callers can recurse into them the same way they recurse into
+    /// `path`'s own generics. Each top-level param is tagged as a type, const or
+    /// lifetime generic argument, so callers can forward const and lifetime
+    /// arguments (e.g. the `{ N }` in `BoundedVec<T, { N }>` or the `'a` in `Cow<'a, T>`)
+    /// without mistaking them for a type to resolve; a bare const ident such as the
+    /// `N` in `BoundedVec<T, N>` is tagged as a type and forwarded like one. The returned params may be
+    /// shorter than the source's own parameter list when the substitute target
+    /// omitted trailing defaulted parameters; the compiler fills those in.
     pub fn for_path_with_params<'a: 'b, 'b>(
         &'a self,
         path: &syn::TypePath,
-        params: &'b [TypePath],
-    ) -> Option<(&'a syn::TypePath, &'b [TypePath])> {
+        params: &'b [GenericParam],
+    ) -> Option<(&'a syn::TypePath, &'b [GenericParam])> {
         // We only support:
         // 1. Reordering the generics
         // 2. Replacing the generic type with a concrete type (won't this affect parent_type_params logic?)
@@ -163,6 +281,248 @@ fn type_args(path_args: &syn::PathArguments) -> impl Iterator<Item = &syn::TypeP
         })
 }
 
+/// Returns an iterator over the generic argument slots of `syn::PathArguments`,
+/// surfacing type, const and lifetime generic parameters.
+/// For example:
+/// - `<'a, T, N>` should return `'a`, `T` and `N`
+/// - `(A, B) -> String` shouldn't return anything
+///
+/// A bare const generic like the `N` above is indistinguishable, at the syntax
+/// level, from a type parameter: `syn` parses a lone ident in generic position
+/// as `GenericArgument::Type(Type::Path(..))` regardless of whether the
+/// declaration behind it is a type or a const, so it's surfaced as
+/// `GenericSlot::Type` and substituted/matched the same way a type ident is.
+/// This only handles `GenericArgument::Const` for const expressions that parse
+/// unambiguously as such (literals, blocks, etc).
+///
+/// `[T; N]`-style fixed-size array arguments (`syn::Type::Array`) aren't
+/// substitutable (there's no sensible way to resolve `T`/`N` independently
+/// inside one), so rather than silently drop the slot and emit a shorter,
+/// wrong param list, this aborts: better to fail at macro-expansion than
+/// generate incorrect code.
+fn generic_args(path_args: &syn::PathArguments) -> impl Iterator<Item = GenericSlot<'_>> {
+    let args_opt = match path_args {
+        syn::PathArguments::AngleBracketed(syn::AngleBracketedGenericArguments {
+            ref args,
+            ..
+        }) => Some(args),
+        _ => None,
+    };
+
+    args_opt
+        .into_iter()
+        .flat_map(|x| x)
+        .filter_map(|arg| match arg {
+            syn::GenericArgument::Type(syn::Type::Path(path)) => Some(GenericSlot::Type(path)),
+            syn::GenericArgument::Const(expr) => Some(GenericSlot::Const(expr)),
+            syn::GenericArgument::Lifetime(lifetime) => Some(GenericSlot::Lifetime(lifetime)),
+            syn::GenericArgument::Type(array @ syn::Type::Array(_)) => {
+                abort!(
+                    array.span(),
+                    "array-typed generic arguments (`[T; N]`) aren't supported in a substitute"
+                )
+            }
+            _ => None,
+        })
+}
+
+/// Strip trailing defaulted generic parameters from a substitute's target path,
+/// mirroring how derive macros normalize a type's generics before re-emitting
+/// them. A user writes a defaulted parameter using associated-type-binding
+/// syntax, e.g. `crate::Map<K, V, S = DefaultHasher>`, purely to document that
+/// `S` is left out on purpose; since it isn't a real argument to forward, it's
+/// dropped from the emitted path and the compiler fills in the default itself.
+///
+/// Returns the idents of the parameters that were stripped (e.g. `S`), so callers
+/// can tell "intentionally defaulted" apart from "actually unused" when a source
+/// generic of the same name never shows up in the remaining target arguments.
+fn strip_defaulted_params(with: &mut syn::TypePath) -> Vec<syn::Ident> {
+    let mut defaulted = Vec::new();
+
+    let Some(syn::PathSegment { arguments, .. }) = with.path.segments.last_mut() else {
+        return defaulted;
+    };
+    let syn::PathArguments::AngleBracketed(bracketed) = arguments else {
+        return defaulted;
+    };
+
+    while matches!(bracketed.args.last(), Some(syn::GenericArgument::AssocType(_))) {
+        let Some(syn::GenericArgument::AssocType(assoc)) = bracketed.args.pop().map(Pair::into_value) else {
+            unreachable!("just matched AssocType above")
+        };
+        defaulted.push(assoc.ident);
+    }
+    if bracketed.args.is_empty() {
+        *arguments = syn::PathArguments::None;
+    }
+
+    defaulted
+}
+
+/// Returns the ident of `ty` if it's a bare, non-absolute, non-generic single
+/// segment path (e.g. `T`), which is the only shape a reference to a declared
+/// generic source parameter can take.
+fn generic_ident(ty: &syn::TypePath) -> Option<&syn::Ident> {
+    if ty.path.leading_colon.is_some() || ty.path.segments.len() != 1 {
+        return None;
+    }
+    let seg = &ty.path.segments[0];
+    seg.arguments.is_none().then(|| &seg.ident)
+}
+
+/// Recursively walk a substitute's target generic arguments and check that each
+/// bare ident (e.g. the `C` in `Bar<C>`) matches one of the source's declared
+/// generic idents, recording it in `used`. Aborts with a single, spanned
+/// diagnostic naming the offending ident and the full set of idents the source
+/// declares. Mirrors [`resolve_substituted_arg`]'s own resolution order: an
+/// absolute concrete arg (e.g. `::std::vec::Vec<u8>`) is left untouched rather
+/// than descended into, and a source ident referenced more than once (e.g.
+/// `Bar<T, PhantomData<T>>`) is accepted, since resolution happily forwards it
+/// to both positions.
+fn validate_target_generics<'a>(
+    target_path_args: &syn::PathArguments,
+    source_idents: &[&'a syn::Ident],
+    used: &mut Vec<&'a syn::Ident>,
+) {
+    for slot in generic_args(target_path_args) {
+        let GenericSlot::Type(arg) = slot else { continue };
+
+        if is_absolute(arg) {
+            continue;
+        }
+
+        if let Some(syn::PathSegment { arguments, .. }) = arg.path.segments.last() {
+            if matches!(arguments, syn::PathArguments::AngleBracketed(a) if !a.args.is_empty()) {
+                validate_target_generics(arguments, source_idents, used);
+                continue;
+            }
+        }
+
+        let Some(ident) = generic_ident(arg) else { continue };
+        let Some(&matched) = source_idents.iter().find(|src| **src == ident) else {
+            let expected = source_idents
+                .iter()
+                .map(|ident| format!("`{ident}`"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            abort!(ident.span(), "unknown generic `{}`; source declares {}", ident, expected);
+        };
+        if !used.contains(&matched) {
+            used.push(matched);
+        }
+    }
+}
+
+/// Recursively walk a substitute's target generic arguments and check that each
+/// lifetime (e.g. the `'a` in `MyCow<'a, T>`) matches one of the source's declared
+/// lifetimes exactly once, recording it in `used`. Mirrors
+/// [`validate_target_generics`], aborting on an unknown lifetime or a duplicate
+/// reorder instead of silently dropping or reusing it.
+fn validate_target_lifetimes<'a>(
+    target_path_args: &syn::PathArguments,
+    source_lifetimes: &[&'a syn::Lifetime],
+    used: &mut Vec<&'a syn::Lifetime>,
+) {
+    for slot in generic_args(target_path_args) {
+        match slot {
+            GenericSlot::Lifetime(lifetime) => {
+                let Some(&matched) = source_lifetimes.iter().find(|src| src.ident == lifetime.ident) else {
+                    let expected = source_lifetimes
+                        .iter()
+                        .map(|lifetime| format!("`{lifetime}`"))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    abort!(lifetime.span(), "unknown lifetime `{}`; source declares {}", lifetime, expected);
+                };
+                if used.contains(&matched) {
+                    abort!(lifetime.span(), "lifetime `{}` can't be substituted more than once", matched);
+                }
+                used.push(matched);
+            }
+            // Lifetimes can be nested inside a target's own container args (e.g.
+            // `Wrapper<Cow<'a, T>>`), so recurse into those too. Unlike
+            // `validate_target_generics`, absolute args are descended into as well.
+            GenericSlot::Type(arg) => {
+                if let Some(syn::PathSegment { arguments, .. }) = arg.path.segments.last() {
+                    if matches!(arguments, syn::PathArguments::AngleBracketed(a) if !a.args.is_empty())
+                    {
+                        validate_target_lifetimes(arguments, source_lifetimes, used);
+                    }
+                }
+            }
+            GenericSlot::Const(_) => {}
+        }
+    }
+}
+
+/// Resolve a single generic argument of a substitute's target path against the
+/// source type's declared generic idents, recursing into its own nested generic
+/// arguments (if any) so that e.g. the `T` in `Vec<T>` gets rewired too.
+///
+/// Resolution order:
+/// 1. The argument matches one of the source's generic idents exactly => substitute it.
+/// 2. The argument is already an absolute path (e.g. `::std::vec::Vec<u8>`) => keep
+///    it exactly as written; it's already valid at the macro's expansion site, so
+///    its own generics (however opaque) are left untouched.
+/// 3. The argument carries its own generic arguments => recurse into them (types,
+///    consts and lifetimes alike), keeping the argument's own bare path (e.g.
+///    `Vec`, `BTreeMap`) and moving its resolved arguments into `params`, rather
+///    than leaving them embedded in `path` too.
+/// 4. Otherwise it's ambiguous and can't be resolved at macro-expansion time.
+fn resolve_substituted_arg(arg: &syn::TypePath, source_args: &[&syn::TypePath]) -> TypePath {
+    if let Some(&src) = source_args.iter().find(|&src| src == &arg) {
+        return TypePath::Type(TypePathType::Path { path: src.clone(), params: Vec::new() });
+    }
+
+    if is_absolute(arg) {
+        return TypePath::Type(TypePathType::Path { path: arg.clone(), params: Vec::new() });
+    }
+
+    let Some(syn::PathSegment { arguments, .. }) = arg.path.segments.last() else {
+        abort!(arg.span(), "Empty path")
+    };
+    let nested_args: Vec<_> = generic_args(arguments).collect();
+    if !nested_args.is_empty() {
+        let params = nested_args
+            .into_iter()
+            .map(|slot| match slot {
+                GenericSlot::Type(nested) => {
+                    GenericParam::Type(resolve_substituted_arg(nested, source_args))
+                }
+                GenericSlot::Const(expr) => GenericParam::Const(expr.clone()),
+                GenericSlot::Lifetime(lifetime) => GenericParam::Lifetime(lifetime.clone()),
+            })
+            .collect();
+        return TypePath::Type(TypePathType::Path {
+            path: without_generic_args(arg),
+            params,
+        });
+    }
+
+    let expected = source_args
+        .iter()
+        .map(|src| format!("`{}`", src.to_token_stream()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    abort!(
+        arg.span(),
+        "unknown generic `{}`; source declares {}",
+        arg.to_token_stream(),
+        expected
+    )
+}
+
+/// Clone `ty` with its last segment's generic arguments stripped, e.g. `Vec<T>`
+/// becomes `Vec`. Used when the arguments are moved into a separate `params` tree
+/// instead, so they aren't represented twice.
+fn without_generic_args(ty: &syn::TypePath) -> syn::TypePath {
+    let mut ty = ty.clone();
+    if let Some(segment) = ty.path.segments.last_mut() {
+        segment.arguments = syn::PathArguments::None;
+    }
+    ty
+}
+
 fn is_absolute(value: &syn::TypePath) -> bool {
     value.path.leading_colon.is_some()
         || value